@@ -6,14 +6,86 @@ use sparse_merkle_tree::{
     H256,
 };
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
 
-use postgres::{Client, NoTls, Statement};
+use postgres::{types::ToSql, Client, NoTls, Statement, Transaction};
+use r2d2_postgres::{r2d2::Pool, PostgresConnectionManager};
+use rusqlite::OptionalExtension;
 
 fn convert_postgres_error(error: postgres::error::Error) -> Error {
     Error::Store(error.to_string())
 }
 
+/// Tag byte marking which `MergeValue` variant an encoded branch side holds.
+const MERGE_TAG_VALUE: u8 = 0;
+const MERGE_TAG_MERGE_WITH_ZERO: u8 = 1;
+
+/// Encode a `MergeValue` into the tagged byte layout stored in `branches_map`.
+///
+/// `Value(H256)` is written as `[tag(0), hash(32)]`; `MergeWithZero` is
+/// written as `[tag(1), base_node(32), zero_bits(32), zero_count(1)]`. This
+/// keeps the optimized zero-subtree representation intact across a
+/// store round-trip instead of collapsing it to its hash.
+fn encode_merge_value(value: &MergeValue) -> Vec<u8> {
+    match value {
+        MergeValue::Value(hash) => {
+            let mut buf = Vec::with_capacity(1 + 32);
+            buf.push(MERGE_TAG_VALUE);
+            buf.extend_from_slice(hash.as_slice());
+            buf
+        }
+        MergeValue::MergeWithZero {
+            base_node,
+            zero_bits,
+            zero_count,
+        } => {
+            let mut buf = Vec::with_capacity(1 + 32 + 32 + 1);
+            buf.push(MERGE_TAG_MERGE_WITH_ZERO);
+            buf.extend_from_slice(base_node.as_slice());
+            buf.extend_from_slice(zero_bits.as_slice());
+            buf.push(*zero_count);
+            buf
+        }
+    }
+}
+
+/// Decode a `MergeValue` previously written by [`encode_merge_value`].
+///
+/// Validates the buffer is long enough for the tag before slicing it, so a
+/// truncated or otherwise corrupt row hits the same `Store` error as an
+/// unrecognized tag instead of panicking.
+fn decode_merge_value(bytes: &[u8]) -> Result<MergeValue, Error> {
+    fn malformed(bytes: &[u8]) -> Error {
+        Error::Store(format!(
+            "malformed MergeValue encoding in branches_map row ({} bytes, tag {:?})",
+            bytes.len(),
+            bytes.first()
+        ))
+    }
+
+    let mut raw = [0u8; 32];
+    match bytes.first() {
+        Some(&MERGE_TAG_VALUE) if bytes.len() == 1 + 32 => {
+            raw.copy_from_slice(&bytes[1..33]);
+            Ok(MergeValue::Value(H256::from(raw)))
+        }
+        Some(&MERGE_TAG_MERGE_WITH_ZERO) if bytes.len() == 1 + 32 + 32 + 1 => {
+            raw.copy_from_slice(&bytes[1..33]);
+            let base_node = H256::from(raw);
+            raw.copy_from_slice(&bytes[33..65]);
+            let zero_bits = H256::from(raw);
+            let zero_count = bytes[65];
+            Ok(MergeValue::MergeWithZero {
+                base_node,
+                zero_bits,
+                zero_count,
+            })
+        }
+        _ => Err(malformed(bytes)),
+    }
+}
+
 pub struct PostgresStore<H: Hasher + Default> {
     client: RefCell<Client>,
     get_branch_statement: Statement,
@@ -22,15 +94,75 @@ pub struct PostgresStore<H: Hasher + Default> {
     insert_leaf_statement: Statement,
     remove_branch_statement: Statement,
     remove_leaf_statement: Statement,
+    /// `Some(version)` once [`Self::enable_history`] has been called: every
+    /// `insert_branch`/`insert_leaf`/`remove_branch`/`remove_leaf` call also
+    /// appends a row tagged with this version to `branches_history`/
+    /// `leaves_history`, so the history tables stay in sync with whatever
+    /// the caller actually does through the `Store<H256>` trait (the path
+    /// `SparseMerkleTree::update` drives) instead of only through a
+    /// separate, easy-to-forget set of versioned methods. Advance it with
+    /// [`Self::set_version`] before each tree update that should land on
+    /// its own version.
+    history_version: Option<i64>,
     _phantom: PhantomData<H>,
 }
 
-fn new_postgres_store<H: Hasher + Default>() -> Result<PostgresStore<H>, postgres::error::Error> {
-    let mut client = Client::connect(
-        "host=127.0.0.1 port=5432 user=postgres password=123456 dbname=smt_benchmark",
-        NoTls,
-    )?;
+/// Connection and initialization settings for [`PostgresStore`].
+///
+/// `initialize` controls whether `branches_map`/`leaves_map` are dropped
+/// and recreated (fresh tree) or left as-is so an existing tree can be
+/// reopened, mirroring the `new`/`load` split other database-backed
+/// trees provide.
+#[derive(Clone)]
+pub struct PostgresConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub dbname: String,
+    pub initialize: bool,
+}
 
+/// Hand-rolled so a `{:?}`/log of the config never leaks `password` in
+/// plaintext the way `#[derive(Debug)]` would.
+impl std::fmt::Debug for PostgresConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("user", &self.user)
+            .field("password", &"<redacted>")
+            .field("dbname", &self.dbname)
+            .field("initialize", &self.initialize)
+            .finish()
+    }
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        PostgresConfig {
+            host: "127.0.0.1".to_string(),
+            port: 5432,
+            user: "postgres".to_string(),
+            password: "123456".to_string(),
+            dbname: "smt_benchmark".to_string(),
+            initialize: true,
+        }
+    }
+}
+
+impl PostgresConfig {
+    fn connection_string(&self) -> String {
+        format!(
+            "host={} port={} user={} password={} dbname={}",
+            self.host, self.port, self.user, self.password, self.dbname
+        )
+    }
+}
+
+/// Create `branches_map`/`leaves_map` from scratch, dropping any existing
+/// tables. Only used when `PostgresConfig::initialize` is set.
+fn create_schema(client: &mut Client) -> Result<(), postgres::error::Error> {
     client.batch_execute("DROP TABLE IF EXISTS leaves_map")?;
     client.batch_execute("DROP TABLE IF EXISTS branches_map")?;
 
@@ -50,7 +182,108 @@ fn new_postgres_store<H: Hasher + Default>() -> Result<PostgresStore<H>, postgre
                 PRIMARY KEY(height, node_key)
             )",
     )?;
+    Ok(())
+}
+
+/// Current on-disk layout of `branches_map`/`leaves_map`, tracked in a
+/// dedicated `schema_version` table. Bump this and add a step to
+/// [`migrate`] whenever the row layout changes (for example the tagged
+/// `MergeValue` encoding `branches_map` uses today).
+const SCHEMA_VERSION: i32 = 1;
 
+fn ensure_schema_version_table(client: &mut Client) -> Result<(), postgres::error::Error> {
+    client.batch_execute("CREATE TABLE IF NOT EXISTS schema_version (version INT NOT NULL)")
+}
+
+fn read_schema_version(client: &mut Client) -> Result<Option<i32>, postgres::error::Error> {
+    let row = client.query_opt("SELECT version FROM schema_version LIMIT 1", &[])?;
+    Ok(row.map(|row| row.get(0)))
+}
+
+fn write_schema_version(client: &mut Client, version: i32) -> Result<(), postgres::error::Error> {
+    client.execute("DELETE FROM schema_version", &[])?;
+    client.execute(
+        "INSERT INTO schema_version (version) VALUES ($1)",
+        &[&version],
+    )?;
+    Ok(())
+}
+
+/// Tag a bare 32-byte pre-v1 hash into the v1 tagged `Value` encoding. A
+/// row already in the tagged layout is at least 33 bytes (`1 + 32` for
+/// `Value`), so a bare 32-byte column unambiguously identifies a v0 row;
+/// anything else is already tagged and is passed through unchanged.
+fn tag_if_bare(bytes: Vec<u8>) -> Vec<u8> {
+    if bytes.len() == 32 {
+        let mut tagged = Vec::with_capacity(1 + 32);
+        tagged.push(MERGE_TAG_VALUE);
+        tagged.extend_from_slice(&bytes);
+        tagged
+    } else {
+        bytes
+    }
+}
+
+/// Rewrite pre-schema-version `branches_map` rows (bare 32-byte
+/// `left_node`/`right_node` hashes, the format `insert_branch` wrote before
+/// the tagged `MergeValue` encoding) into the v1 tagged layout
+/// `decode_merge_value` expects, via [`tag_if_bare`].
+fn migrate_v0_to_v1(client: &mut Client) -> Result<(), postgres::error::Error> {
+    let rows = client.query(
+        "SELECT height, node_key, left_node, right_node FROM branches_map",
+        &[],
+    )?;
+
+    for row in rows {
+        let height: i32 = row.get(0);
+        let node_key: Vec<u8> = row.get(1);
+        let left: Vec<u8> = row.get(2);
+        let right: Vec<u8> = row.get(3);
+
+        if left.len() != 32 && right.len() != 32 {
+            continue;
+        }
+
+        client.execute(
+            "UPDATE branches_map SET left_node = $1, right_node = $2
+                WHERE height = $3 AND node_key = $4",
+            &[&tag_if_bare(left), &tag_if_bare(right), &height, &node_key],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Detect the on-disk schema version and upgrade `branches_map`/
+/// `leaves_map` rows forward to [`SCHEMA_VERSION`], run once at open
+/// time so a newer `PostgresStore` never silently misreads older rows.
+fn migrate(client: &mut Client) -> Result<(), postgres::error::Error> {
+    ensure_schema_version_table(client)?;
+    let mut version = read_schema_version(client)?.unwrap_or(0);
+
+    if version == 0 {
+        migrate_v0_to_v1(client)?;
+        version = SCHEMA_VERSION;
+    }
+
+    // Future row-format changes add a migration step here, e.g.:
+    // if version == 1 { rewrite_branches_map_to_v2(client)?; version = 2; }
+
+    write_schema_version(client, version)
+}
+
+fn new_postgres_store<H: Hasher + Default>(
+    config: &PostgresConfig,
+) -> Result<PostgresStore<H>, postgres::error::Error> {
+    let mut client = Client::connect(&config.connection_string(), NoTls)?;
+
+    if config.initialize {
+        create_schema(&mut client)?;
+    }
+    migrate(&mut client)?;
+
+    // `left_node`/`right_node` hold the tagged `MergeValue` encoding produced
+    // by `encode_merge_value`, not a bare 32-byte hash.
     let get_branch_statement = client.prepare(
         "SELECT left_node, right_node FROM branches_map WHERE height = $1 AND node_key = $2",
     )?;
@@ -79,13 +312,490 @@ fn new_postgres_store<H: Hasher + Default>() -> Result<PostgresStore<H>, postgre
         insert_leaf_statement,
         remove_branch_statement,
         remove_leaf_statement,
+        history_version: None,
         _phantom: PhantomData,
     })
 }
 
 impl<H: Hasher + Default> PostgresStore<H> {
     pub fn new() -> Result<Self, Error> {
-        new_postgres_store().map_err(convert_postgres_error)
+        Self::with_config(PostgresConfig::default())
+    }
+
+    /// Open a store against the given connection settings. When
+    /// `config.initialize` is `false` the existing `branches_map`/
+    /// `leaves_map` tables are reused as-is instead of being dropped,
+    /// so a tree written by a previous run can be reopened.
+    pub fn with_config(config: PostgresConfig) -> Result<Self, Error> {
+        new_postgres_store(&config).map_err(convert_postgres_error)
+    }
+
+    /// Write a batch of branch nodes with a single multi-row `INSERT ...
+    /// ON CONFLICT` statement instead of one round-trip per node. This is
+    /// the batched counterpart to [`Store::insert_branch`] and is meant to
+    /// be used for the dozens of nodes a single tree update touches along
+    /// its height.
+    pub fn insert_branch_batch(
+        &mut self,
+        branches: Vec<(BranchKey, BranchNode)>,
+    ) -> Result<(), Error> {
+        if branches.is_empty() {
+            return Ok(());
+        }
+
+        let heights: Vec<i32> = branches
+            .iter()
+            .map(|(branch_key, _)| i32::from(branch_key.height))
+            .collect();
+        let node_keys: Vec<Vec<u8>> = branches
+            .iter()
+            .map(|(branch_key, _)| branch_key.node_key.as_slice().to_vec())
+            .collect();
+        let lefts: Vec<Vec<u8>> = branches
+            .iter()
+            .map(|(_, branch)| encode_merge_value(&branch.left))
+            .collect();
+        let rights: Vec<Vec<u8>> = branches
+            .iter()
+            .map(|(_, branch)| encode_merge_value(&branch.right))
+            .collect();
+
+        let mut query = String::from(
+            "INSERT INTO branches_map (height, node_key, left_node, right_node) VALUES ",
+        );
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(branches.len() * 4);
+        for i in 0..branches.len() {
+            if i > 0 {
+                query.push_str(", ");
+            }
+            let base = i * 4;
+            query.push_str(&format!(
+                "(${}, ${}, ${}, ${})",
+                base + 1,
+                base + 2,
+                base + 3,
+                base + 4
+            ));
+            params.push(&heights[i]);
+            params.push(&node_keys[i]);
+            params.push(&lefts[i]);
+            params.push(&rights[i]);
+        }
+        query.push_str(
+            " ON CONFLICT(height, node_key) DO UPDATE \
+             SET left_node = EXCLUDED.left_node, right_node = EXCLUDED.right_node",
+        );
+
+        for (i, (branch_key, _)) in branches.iter().enumerate() {
+            self.append_branch_history(branch_key, Some(lefts[i].as_slice()), Some(rights[i].as_slice()))?;
+        }
+        self.client
+            .borrow_mut()
+            .execute(query.as_str(), &params)
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+
+    /// Write a batch of leaves with a single multi-row `INSERT ... ON
+    /// CONFLICT` statement instead of one round-trip per leaf.
+    pub fn insert_leaf_batch(&mut self, leaves: Vec<(H256, H256)>) -> Result<(), Error> {
+        if leaves.is_empty() {
+            return Ok(());
+        }
+
+        let keys: Vec<Vec<u8>> = leaves
+            .iter()
+            .map(|(key, _)| key.as_slice().to_vec())
+            .collect();
+        let values: Vec<Vec<u8>> = leaves
+            .iter()
+            .map(|(_, value)| value.as_slice().to_vec())
+            .collect();
+
+        let mut query = String::from("INSERT INTO leaves_map (key, value) VALUES ");
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(leaves.len() * 2);
+        for i in 0..leaves.len() {
+            if i > 0 {
+                query.push_str(", ");
+            }
+            query.push_str(&format!("(${}, ${})", i * 2 + 1, i * 2 + 2));
+            params.push(&keys[i]);
+            params.push(&values[i]);
+        }
+        query.push_str(" ON CONFLICT(key) DO UPDATE SET value = EXCLUDED.value");
+
+        for (i, (key, _)) in leaves.iter().enumerate() {
+            self.append_leaf_history(key, Some(values[i].as_slice()))?;
+        }
+        self.client
+            .borrow_mut()
+            .execute(query.as_str(), &params)
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+
+    /// Open a transaction that routes `insert_*`/`remove_*`/`get_*` through
+    /// a single `postgres::Transaction`, left uncommitted until the caller
+    /// calls [`PostgresTransaction::commit`] (or rolls back).
+    pub fn begin(&mut self) -> Result<PostgresTransaction<'_, H>, Error> {
+        let tx = self
+            .client
+            .get_mut()
+            .transaction()
+            .map_err(convert_postgres_error)?;
+        PostgresTransaction::new(tx).map_err(convert_postgres_error)
+    }
+
+    /// Run `f` inside a single Postgres transaction. The transaction is
+    /// committed only when `f` returns `Ok`; any `Err` (or dropping `tx`
+    /// without committing) rolls the transaction back, so a failure
+    /// midway through a tree update never leaves `branches_map`/
+    /// `leaves_map` in a partially-updated state.
+    pub fn with_transaction<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut PostgresTransaction<H>) -> Result<(), Error>,
+    {
+        let mut tx = self.begin()?;
+        match f(&mut tx) {
+            Ok(()) => tx.commit(),
+            Err(err) => {
+                let _ = tx.rollback();
+                Err(err)
+            }
+        }
+    }
+}
+
+/// A single Postgres transaction over `branches_map`/`leaves_map`. Mirrors
+/// [`PostgresStore`]'s `Store` impl but keeps every write uncommitted until
+/// [`PostgresTransaction::commit`] is called. Writes made through it are not
+/// recorded in `branches_history`/`leaves_history`, even when
+/// [`PostgresStore::enable_history`] has been called.
+pub struct PostgresTransaction<'a, H: Hasher + Default> {
+    tx: RefCell<Transaction<'a>>,
+    get_branch_statement: Statement,
+    get_leaf_statement: Statement,
+    insert_branch_statement: Statement,
+    insert_leaf_statement: Statement,
+    remove_branch_statement: Statement,
+    remove_leaf_statement: Statement,
+    _phantom: PhantomData<H>,
+}
+
+impl<'a, H: Hasher + Default> PostgresTransaction<'a, H> {
+    fn new(mut tx: Transaction<'a>) -> Result<Self, postgres::error::Error> {
+        let get_branch_statement = tx.prepare(
+            "SELECT left_node, right_node FROM branches_map WHERE height = $1 AND node_key = $2",
+        )?;
+        let get_leaf_statement = tx.prepare("SELECT value FROM leaves_map WHERE key = $1")?;
+        let insert_branch_statement = tx.prepare(
+            "INSERT INTO branches_map (
+                    height,
+                    node_key,
+                    left_node,
+                    right_node
+                ) VALUES ($1, $2, $3, $4)
+                ON CONFLICT(height, node_key) DO
+                UPDATE SET left_node = $3, right_node = $4",
+        )?;
+        let insert_leaf_statement =
+            tx.prepare("INSERT INTO leaves_map (key, value) VALUES ($1, $2)")?;
+        let remove_branch_statement =
+            tx.prepare("DELETE FROM branches_map WHERE height = $1 AND node_key = $2")?;
+        let remove_leaf_statement = tx.prepare("DELETE FROM leaves_map WHERE key = $1")?;
+
+        Ok(Self {
+            tx: RefCell::new(tx),
+            get_branch_statement,
+            get_leaf_statement,
+            insert_branch_statement,
+            insert_leaf_statement,
+            remove_branch_statement,
+            remove_leaf_statement,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Commit every write made through this transaction.
+    pub fn commit(self) -> Result<(), Error> {
+        self.tx
+            .into_inner()
+            .commit()
+            .map_err(convert_postgres_error)
+    }
+
+    /// Discard every write made through this transaction.
+    pub fn rollback(self) -> Result<(), Error> {
+        self.tx
+            .into_inner()
+            .rollback()
+            .map_err(convert_postgres_error)
+    }
+}
+
+impl<'a, H: Hasher + Default> Store<H256> for PostgresTransaction<'a, H> {
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        let row = self
+            .tx
+            .borrow_mut()
+            .query_opt(
+                &self.get_branch_statement,
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let raw_left: &[u8] = row.get(0);
+                let raw_right: &[u8] = row.get(1);
+
+                Ok(Some(BranchNode {
+                    left: decode_merge_value(raw_left)?,
+                    right: decode_merge_value(raw_right)?,
+                }))
+            }
+        }
+    }
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<H256>, Error> {
+        let row = self
+            .tx
+            .borrow_mut()
+            .query_opt(&self.get_leaf_statement, &[&leaf_key.as_slice()])
+            .map_err(convert_postgres_error)?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let mut raw_value: [u8; 32] = [0; 32];
+                raw_value.copy_from_slice(row.get(0));
+
+                let value: H256 = H256::from(raw_value);
+
+                Ok(Some(value))
+            }
+        }
+    }
+    fn insert_branch(&mut self, branch_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let left = encode_merge_value(&branch.left);
+        let right = encode_merge_value(&branch.right);
+        self.tx
+            .borrow_mut()
+            .execute(
+                &self.insert_branch_statement,
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                    &left,
+                    &right,
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: H256) -> Result<(), Error> {
+        self.tx
+            .borrow_mut()
+            .execute(
+                &self.insert_leaf_statement,
+                &[&leaf_key.as_slice(), &leaf.as_slice()],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn remove_branch(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        self.tx
+            .borrow_mut()
+            .execute(
+                &self.remove_branch_statement,
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.tx
+            .borrow_mut()
+            .execute(&self.remove_leaf_statement, &[&leaf_key.as_slice()])
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+}
+
+impl<H: Hasher + Default> PostgresStore<H> {
+    /// Create the append-only history tables used by [`Self::get_branch_at`]
+    /// / [`Self::get_leaf_at`] and start tagging every write at version 0.
+    /// Idempotent; only needed by callers that want to reconstruct proofs
+    /// against a past root rather than just the current one, so it's
+    /// opt-in instead of part of `new`/`with_config`.
+    ///
+    /// `left_node`/`right_node`/`value` are nullable here (unlike
+    /// `branches_map`/`leaves_map`): a `NULL` row is a tombstone recording
+    /// that the node was removed as of that version, so
+    /// [`Self::get_branch_at`]/[`Self::get_leaf_at`] can tell "deleted by
+    /// version V" apart from "never written".
+    pub fn enable_history(&mut self) -> Result<(), Error> {
+        self.client
+            .get_mut()
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS branches_history (
+                        version     BIGINT NOT NULL,
+                        height      INT NOT NULL,
+                        node_key    BYTEA NOT NULL,
+                        left_node   BYTEA,
+                        right_node  BYTEA,
+                        PRIMARY KEY(height, node_key, version)
+                    )",
+            )
+            .map_err(convert_postgres_error)?;
+        self.client
+            .get_mut()
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS leaves_history (
+                        version BIGINT NOT NULL,
+                        key     BYTEA NOT NULL,
+                        value   BYTEA,
+                        PRIMARY KEY(key, version)
+                    )",
+            )
+            .map_err(convert_postgres_error)?;
+        self.history_version = Some(0);
+        Ok(())
+    }
+
+    /// Advance the version that subsequent `insert_*`/`remove_*` calls are
+    /// tagged with in the history tables. Callers driving a tree through
+    /// the normal `Store` API should call this once per logical update
+    /// (e.g. once per `tree.update()`) so each update lands on its own
+    /// version instead of all sharing version 0. A no-op if
+    /// [`Self::enable_history`] hasn't been called yet.
+    pub fn set_version(&mut self, version: i64) {
+        if self.history_version.is_some() {
+            self.history_version = Some(version);
+        }
+    }
+
+    fn append_branch_history(
+        &self,
+        branch_key: &BranchKey,
+        left: Option<&[u8]>,
+        right: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let Some(version) = self.history_version else {
+            return Ok(());
+        };
+        self.client
+            .borrow_mut()
+            .execute(
+                "INSERT INTO branches_history (version, height, node_key, left_node, right_node)
+                    VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (height, node_key, version) DO UPDATE
+                    SET left_node = $4, right_node = $5",
+                &[
+                    &version,
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                    &left,
+                    &right,
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+
+    fn append_leaf_history(&self, leaf_key: &H256, value: Option<&[u8]>) -> Result<(), Error> {
+        let Some(version) = self.history_version else {
+            return Ok(());
+        };
+        self.client
+            .borrow_mut()
+            .execute(
+                "INSERT INTO leaves_history (version, key, value) VALUES ($1, $2, $3)
+                    ON CONFLICT (key, version) DO UPDATE SET value = $3",
+                &[&version, &leaf_key.as_slice(), &value],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+
+    /// Reconstruct the branch node for `branch_key` as it stood at
+    /// `version`, by selecting the greatest recorded version ≤ the
+    /// requested one. Returns `Ok(None)` both when the node was never
+    /// written by that version and when it was since removed (a tombstone
+    /// row, recorded as `NULL` columns).
+    pub fn get_branch_at(
+        &self,
+        version: i64,
+        branch_key: &BranchKey,
+    ) -> Result<Option<BranchNode>, Error> {
+        let row = self
+            .client
+            .borrow_mut()
+            .query_opt(
+                "SELECT left_node, right_node FROM branches_history
+                    WHERE height = $1 AND node_key = $2 AND version <= $3
+                    ORDER BY version DESC LIMIT 1",
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                    &version,
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let raw_left: Option<Vec<u8>> = row.get(0);
+                let raw_right: Option<Vec<u8>> = row.get(1);
+                match (raw_left, raw_right) {
+                    (Some(left), Some(right)) => Ok(Some(BranchNode {
+                        left: decode_merge_value(&left)?,
+                        right: decode_merge_value(&right)?,
+                    })),
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Reconstruct the leaf value for `leaf_key` as it stood at `version`,
+    /// by selecting the greatest recorded version ≤ the requested one.
+    /// Returns `Ok(None)` both when the leaf was never written by that
+    /// version and when it was since removed (a tombstone row, recorded
+    /// as a `NULL` value).
+    pub fn get_leaf_at(&self, version: i64, leaf_key: &H256) -> Result<Option<H256>, Error> {
+        let row = self
+            .client
+            .borrow_mut()
+            .query_opt(
+                "SELECT value FROM leaves_history
+                    WHERE key = $1 AND version <= $2
+                    ORDER BY version DESC LIMIT 1",
+                &[&leaf_key.as_slice(), &version],
+            )
+            .map_err(convert_postgres_error)?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let raw_value: Option<Vec<u8>> = row.get(0);
+                match raw_value {
+                    Some(raw_value) => {
+                        let mut buf = [0u8; 32];
+                        buf.copy_from_slice(&raw_value);
+                        Ok(Some(H256::from(buf)))
+                    }
+                    None => Ok(None),
+                }
+            }
+        }
     }
 }
 
@@ -112,17 +822,12 @@ impl<H: Hasher + Default> Store<H256> for PostgresStore<H> {
         match row {
             None => Ok(None),
             Some(row) => {
-                let mut raw_left: [u8; 32] = [0; 32];
-                let mut raw_right: [u8; 32] = [0; 32];
-                raw_left.copy_from_slice(row.get(0));
-                raw_right.copy_from_slice(row.get(1));
-
-                let left = H256::from(raw_left);
-                let right = H256::from(raw_right);
+                let raw_left: &[u8] = row.get(0);
+                let raw_right: &[u8] = row.get(1);
 
                 Ok(Some(BranchNode {
-                    left: MergeValue::from_h256(left),
-                    right: MergeValue::from_h256(right),
+                    left: decode_merge_value(raw_left)?,
+                    right: decode_merge_value(raw_right)?,
                 }))
             }
         }
@@ -147,6 +852,9 @@ impl<H: Hasher + Default> Store<H256> for PostgresStore<H> {
         }
     }
     fn insert_branch(&mut self, branch_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let left = encode_merge_value(&branch.left);
+        let right = encode_merge_value(&branch.right);
+        self.append_branch_history(&branch_key, Some(&left), Some(&right))?;
         self.client
             .borrow_mut()
             .execute(
@@ -154,14 +862,15 @@ impl<H: Hasher + Default> Store<H256> for PostgresStore<H> {
                 &[
                     &i32::from(branch_key.height),
                     &branch_key.node_key.as_slice(),
-                    &branch.left.hash::<H>().as_slice(),
-                    &branch.right.hash::<H>().as_slice(),
+                    &left,
+                    &right,
                 ],
             )
             .map_err(convert_postgres_error)?;
         Ok(())
     }
     fn insert_leaf(&mut self, leaf_key: H256, leaf: H256) -> Result<(), Error> {
+        self.append_leaf_history(&leaf_key, Some(leaf.as_slice()))?;
         self.client
             .borrow_mut()
             .execute(
@@ -172,6 +881,7 @@ impl<H: Hasher + Default> Store<H256> for PostgresStore<H> {
         Ok(())
     }
     fn remove_branch(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        self.append_branch_history(branch_key, None, None)?;
         self.client
             .borrow_mut()
             .execute(
@@ -185,6 +895,327 @@ impl<H: Hasher + Default> Store<H256> for PostgresStore<H> {
         Ok(())
     }
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.append_leaf_history(leaf_key, None)?;
+        self.client
+            .borrow_mut()
+            .execute(&self.remove_leaf_statement, &[&leaf_key.as_slice()])
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+}
+
+/// A connection-pool-backed counterpart to [`PostgresStore`]. Each call
+/// checks out its own pooled connection instead of serializing on a
+/// single `RefCell<Client>`, so `get_branch`/`get_leaf` can serve
+/// concurrent read proofs.
+pub struct PostgresPoolStore<H: Hasher + Default> {
+    pool: Pool<PostgresConnectionManager<NoTls>>,
+    _phantom: PhantomData<H>,
+}
+
+impl<H: Hasher + Default> PostgresPoolStore<H> {
+    /// Build a pooled store with up to `pool_size` concurrent connections.
+    /// Schema creation follows `config.initialize` the same way
+    /// [`PostgresStore::with_config`] does.
+    pub fn with_config(config: PostgresConfig, pool_size: u32) -> Result<Self, Error> {
+        let pg_config: postgres::Config = config
+            .connection_string()
+            .parse()
+            .map_err(convert_postgres_error)?;
+
+        {
+            let mut client = pg_config.connect(NoTls).map_err(convert_postgres_error)?;
+            if config.initialize {
+                create_schema(&mut client).map_err(convert_postgres_error)?;
+            }
+            migrate(&mut client).map_err(convert_postgres_error)?;
+        }
+
+        let manager = PostgresConnectionManager::new(pg_config, NoTls);
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .build(manager)
+            .map_err(|err| Error::Store(err.to_string()))?;
+
+        Ok(PostgresPoolStore {
+            pool,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<H: Hasher + Default> Store<H256> for PostgresPoolStore<H> {
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .map_err(|err| Error::Store(err.to_string()))?;
+        let row = conn
+            .query_opt(
+                "SELECT left_node, right_node FROM branches_map WHERE height = $1 AND node_key = $2",
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let raw_left: &[u8] = row.get(0);
+                let raw_right: &[u8] = row.get(1);
+
+                Ok(Some(BranchNode {
+                    left: decode_merge_value(raw_left)?,
+                    right: decode_merge_value(raw_right)?,
+                }))
+            }
+        }
+    }
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<H256>, Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .map_err(|err| Error::Store(err.to_string()))?;
+        let row = conn
+            .query_opt(
+                "SELECT value FROM leaves_map WHERE key = $1",
+                &[&leaf_key.as_slice()],
+            )
+            .map_err(convert_postgres_error)?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let mut raw_value: [u8; 32] = [0; 32];
+                raw_value.copy_from_slice(row.get(0));
+
+                let value: H256 = H256::from(raw_value);
+
+                Ok(Some(value))
+            }
+        }
+    }
+    fn insert_branch(&mut self, branch_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let left = encode_merge_value(&branch.left);
+        let right = encode_merge_value(&branch.right);
+        let mut conn = self
+            .pool
+            .get()
+            .map_err(|err| Error::Store(err.to_string()))?;
+        conn.execute(
+            "INSERT INTO branches_map (
+                    height,
+                    node_key,
+                    left_node,
+                    right_node
+                ) VALUES ($1, $2, $3, $4)
+                ON CONFLICT(height, node_key) DO
+                UPDATE SET left_node = $3, right_node = $4",
+            &[
+                &i32::from(branch_key.height),
+                &branch_key.node_key.as_slice(),
+                &left,
+                &right,
+            ],
+        )
+        .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: H256) -> Result<(), Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .map_err(|err| Error::Store(err.to_string()))?;
+        conn.execute(
+            "INSERT INTO leaves_map (key, value) VALUES ($1, $2)",
+            &[&leaf_key.as_slice(), &leaf.as_slice()],
+        )
+        .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn remove_branch(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .map_err(|err| Error::Store(err.to_string()))?;
+        conn.execute(
+            "DELETE FROM branches_map WHERE height = $1 AND node_key = $2",
+            &[
+                &i32::from(branch_key.height),
+                &branch_key.node_key.as_slice(),
+            ],
+        )
+        .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .map_err(|err| Error::Store(err.to_string()))?;
+        conn.execute(
+            "DELETE FROM leaves_map WHERE key = $1",
+            &[&leaf_key.as_slice()],
+        )
+        .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+}
+
+/// A generic key/value backend for the two tables an SMT store needs:
+/// branch nodes keyed by `(height, node_key)` and leaves keyed by a
+/// `H256`. Implementing this trait is enough to back a full
+/// `Store<H256>` via [`KvStore`] — only the tagged `MergeValue`
+/// (de)serialization lives outside the backend, shared by every impl.
+///
+/// [`PostgresStore`] implements this directly (below), reusing its own
+/// prepared statements, so there is exactly one piece of Postgres SQL for
+/// each operation — a `KvStore<PostgresStore<H>, H>` and a bare
+/// `PostgresStore<H>` hit the same queries. SQLite and the in-memory map
+/// get their own small, genuinely independent impls below.
+pub trait KvBackend {
+    fn get_branch_row(&self, branch_key: &BranchKey) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error>;
+    fn get_leaf_row(&self, leaf_key: &H256) -> Result<Option<Vec<u8>>, Error>;
+    fn put_branch_row(
+        &mut self,
+        branch_key: BranchKey,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    ) -> Result<(), Error>;
+    fn put_leaf_row(&mut self, leaf_key: H256, value: Vec<u8>) -> Result<(), Error>;
+    fn delete_branch_row(&mut self, branch_key: &BranchKey) -> Result<(), Error>;
+    fn delete_leaf_row(&mut self, leaf_key: &H256) -> Result<(), Error>;
+}
+
+/// `Store<H256>` implementation shared by every [`KvBackend`]. Lets the
+/// same SMT store logic sit on top of Postgres, SQLite, or an in-memory
+/// map — whichever backend a deployment picks via config.
+pub struct KvStore<B: KvBackend, H: Hasher + Default> {
+    backend: B,
+    _phantom: PhantomData<H>,
+}
+
+impl<B: KvBackend, H: Hasher + Default> KvStore<B, H> {
+    pub fn new(backend: B) -> Self {
+        KvStore {
+            backend,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<B: KvBackend, H: Hasher + Default> Store<H256> for KvStore<B, H> {
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        match self.backend.get_branch_row(branch_key)? {
+            None => Ok(None),
+            Some((left, right)) => Ok(Some(BranchNode {
+                left: decode_merge_value(&left)?,
+                right: decode_merge_value(&right)?,
+            })),
+        }
+    }
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<H256>, Error> {
+        match self.backend.get_leaf_row(leaf_key)? {
+            None => Ok(None),
+            Some(bytes) => {
+                let mut raw = [0u8; 32];
+                raw.copy_from_slice(&bytes);
+                Ok(Some(H256::from(raw)))
+            }
+        }
+    }
+    fn insert_branch(&mut self, branch_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let left = encode_merge_value(&branch.left);
+        let right = encode_merge_value(&branch.right);
+        self.backend.put_branch_row(branch_key, left, right)
+    }
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: H256) -> Result<(), Error> {
+        self.backend
+            .put_leaf_row(leaf_key, leaf.as_slice().to_vec())
+    }
+    fn remove_branch(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        self.backend.delete_branch_row(branch_key)
+    }
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.backend.delete_leaf_row(leaf_key)
+    }
+}
+
+/// [`PostgresStore`] as a [`KvBackend`], reusing its own prepared
+/// statements instead of hand-writing a second copy of the same SQL —
+/// `KvStore<PostgresStore<H>, H>` and a bare `PostgresStore<H>` now issue
+/// byte-for-byte identical queries, so there's no risk of the two drifting
+/// apart (e.g. disagreeing on `ON CONFLICT` semantics for a leaf insert).
+impl<H: Hasher + Default> KvBackend for PostgresStore<H> {
+    fn get_branch_row(&self, branch_key: &BranchKey) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        let row = self
+            .client
+            .borrow_mut()
+            .query_opt(
+                &self.get_branch_statement,
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(row.map(|row| (row.get(0), row.get(1))))
+    }
+    fn get_leaf_row(&self, leaf_key: &H256) -> Result<Option<Vec<u8>>, Error> {
+        let row = self
+            .client
+            .borrow_mut()
+            .query_opt(&self.get_leaf_statement, &[&leaf_key.as_slice()])
+            .map_err(convert_postgres_error)?;
+        Ok(row.map(|row| row.get(0)))
+    }
+    fn put_branch_row(
+        &mut self,
+        branch_key: BranchKey,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.append_branch_history(&branch_key, Some(left.as_slice()), Some(right.as_slice()))?;
+        self.client
+            .borrow_mut()
+            .execute(
+                &self.insert_branch_statement,
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                    &left,
+                    &right,
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn put_leaf_row(&mut self, leaf_key: H256, value: Vec<u8>) -> Result<(), Error> {
+        self.append_leaf_history(&leaf_key, Some(value.as_slice()))?;
+        self.client
+            .borrow_mut()
+            .execute(&self.insert_leaf_statement, &[&leaf_key.as_slice(), &value])
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn delete_branch_row(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        self.append_branch_history(branch_key, None, None)?;
+        self.client
+            .borrow_mut()
+            .execute(
+                &self.remove_branch_statement,
+                &[
+                    &i32::from(branch_key.height),
+                    &branch_key.node_key.as_slice(),
+                ],
+            )
+            .map_err(convert_postgres_error)?;
+        Ok(())
+    }
+    fn delete_leaf_row(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.append_leaf_history(leaf_key, None)?;
         self.client
             .borrow_mut()
             .execute(&self.remove_leaf_statement, &[&leaf_key.as_slice()])
@@ -192,3 +1223,309 @@ impl<H: Hasher + Default> Store<H256> for PostgresStore<H> {
         Ok(())
     }
 }
+
+/// SQLite-backed [`KvBackend`], for deployments that want on-disk
+/// durability without running a Postgres server.
+pub struct SqliteBackend {
+    conn: RefCell<rusqlite::Connection>,
+}
+
+impl SqliteBackend {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let conn = rusqlite::Connection::open(path).map_err(|err| Error::Store(err.to_string()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS leaves_map (
+                    key     BLOB PRIMARY KEY,
+                    value   BLOB NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS branches_map (
+                    height      INTEGER NOT NULL,
+                    node_key    BLOB NOT NULL,
+                    left_node   BLOB NOT NULL,
+                    right_node  BLOB NOT NULL,
+                    PRIMARY KEY(height, node_key)
+                );",
+        )
+        .map_err(|err| Error::Store(err.to_string()))?;
+        Ok(SqliteBackend {
+            conn: RefCell::new(conn),
+        })
+    }
+}
+
+impl KvBackend for SqliteBackend {
+    fn get_branch_row(&self, branch_key: &BranchKey) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        self.conn
+            .borrow()
+            .query_row(
+                "SELECT left_node, right_node FROM branches_map WHERE height = ?1 AND node_key = ?2",
+                rusqlite::params![i32::from(branch_key.height), branch_key.node_key.as_slice()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|err| Error::Store(err.to_string()))
+    }
+    fn get_leaf_row(&self, leaf_key: &H256) -> Result<Option<Vec<u8>>, Error> {
+        self.conn
+            .borrow()
+            .query_row(
+                "SELECT value FROM leaves_map WHERE key = ?1",
+                rusqlite::params![leaf_key.as_slice()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|err| Error::Store(err.to_string()))
+    }
+    fn put_branch_row(
+        &mut self,
+        branch_key: BranchKey,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.conn
+            .borrow_mut()
+            .execute(
+                "INSERT INTO branches_map (height, node_key, left_node, right_node)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT(height, node_key) DO UPDATE
+                    SET left_node = excluded.left_node, right_node = excluded.right_node",
+                rusqlite::params![
+                    i32::from(branch_key.height),
+                    branch_key.node_key.as_slice(),
+                    left,
+                    right
+                ],
+            )
+            .map_err(|err| Error::Store(err.to_string()))?;
+        Ok(())
+    }
+    fn put_leaf_row(&mut self, leaf_key: H256, value: Vec<u8>) -> Result<(), Error> {
+        self.conn
+            .borrow_mut()
+            .execute(
+                "INSERT INTO leaves_map (key, value) VALUES (?1, ?2)
+                    ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                rusqlite::params![leaf_key.as_slice(), value],
+            )
+            .map_err(|err| Error::Store(err.to_string()))?;
+        Ok(())
+    }
+    fn delete_branch_row(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        self.conn
+            .borrow_mut()
+            .execute(
+                "DELETE FROM branches_map WHERE height = ?1 AND node_key = ?2",
+                rusqlite::params![i32::from(branch_key.height), branch_key.node_key.as_slice()],
+            )
+            .map_err(|err| Error::Store(err.to_string()))?;
+        Ok(())
+    }
+    fn delete_leaf_row(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.conn
+            .borrow_mut()
+            .execute(
+                "DELETE FROM leaves_map WHERE key = ?1",
+                rusqlite::params![leaf_key.as_slice()],
+            )
+            .map_err(|err| Error::Store(err.to_string()))?;
+        Ok(())
+    }
+}
+
+/// In-memory [`KvBackend`], useful for tests and for deployments that
+/// don't need Postgres-backed durability.
+#[derive(Default)]
+pub struct MemoryBackend {
+    branches: BTreeMap<(i32, Vec<u8>), (Vec<u8>, Vec<u8>)>,
+    leaves: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KvBackend for MemoryBackend {
+    fn get_branch_row(&self, branch_key: &BranchKey) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        let key = (
+            i32::from(branch_key.height),
+            branch_key.node_key.as_slice().to_vec(),
+        );
+        Ok(self.branches.get(&key).cloned())
+    }
+    fn get_leaf_row(&self, leaf_key: &H256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.leaves.get(leaf_key.as_slice()).cloned())
+    }
+    fn put_branch_row(
+        &mut self,
+        branch_key: BranchKey,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    ) -> Result<(), Error> {
+        let key = (
+            i32::from(branch_key.height),
+            branch_key.node_key.as_slice().to_vec(),
+        );
+        self.branches.insert(key, (left, right));
+        Ok(())
+    }
+    fn put_leaf_row(&mut self, leaf_key: H256, value: Vec<u8>) -> Result<(), Error> {
+        self.leaves.insert(leaf_key.as_slice().to_vec(), value);
+        Ok(())
+    }
+    fn delete_branch_row(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        let key = (
+            i32::from(branch_key.height),
+            branch_key.node_key.as_slice().to_vec(),
+        );
+        self.branches.remove(&key);
+        Ok(())
+    }
+    fn delete_leaf_row(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.leaves.remove(leaf_key.as_slice());
+        Ok(())
+    }
+}
+
+/// Which [`KvBackend`] a `KvStore` should open, chosen at config time
+/// rather than compiled in.
+pub enum BackendConfig {
+    Postgres(PostgresConfig),
+    Sqlite(String),
+    Memory,
+}
+
+/// [`KvBackend`] that dispatches to whichever concrete backend
+/// [`BackendConfig`] selected. Generic over `H` only because the
+/// `Postgres` variant holds a [`PostgresStore<H>`] — `H` never otherwise
+/// affects this type's own behavior.
+pub enum AnyBackend<H: Hasher + Default> {
+    Postgres(PostgresStore<H>),
+    Sqlite(SqliteBackend),
+    Memory(MemoryBackend),
+}
+
+impl<H: Hasher + Default> KvBackend for AnyBackend<H> {
+    fn get_branch_row(&self, branch_key: &BranchKey) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        match self {
+            AnyBackend::Postgres(backend) => backend.get_branch_row(branch_key),
+            AnyBackend::Sqlite(backend) => backend.get_branch_row(branch_key),
+            AnyBackend::Memory(backend) => backend.get_branch_row(branch_key),
+        }
+    }
+    fn get_leaf_row(&self, leaf_key: &H256) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            AnyBackend::Postgres(backend) => backend.get_leaf_row(leaf_key),
+            AnyBackend::Sqlite(backend) => backend.get_leaf_row(leaf_key),
+            AnyBackend::Memory(backend) => backend.get_leaf_row(leaf_key),
+        }
+    }
+    fn put_branch_row(
+        &mut self,
+        branch_key: BranchKey,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    ) -> Result<(), Error> {
+        match self {
+            AnyBackend::Postgres(backend) => backend.put_branch_row(branch_key, left, right),
+            AnyBackend::Sqlite(backend) => backend.put_branch_row(branch_key, left, right),
+            AnyBackend::Memory(backend) => backend.put_branch_row(branch_key, left, right),
+        }
+    }
+    fn put_leaf_row(&mut self, leaf_key: H256, value: Vec<u8>) -> Result<(), Error> {
+        match self {
+            AnyBackend::Postgres(backend) => backend.put_leaf_row(leaf_key, value),
+            AnyBackend::Sqlite(backend) => backend.put_leaf_row(leaf_key, value),
+            AnyBackend::Memory(backend) => backend.put_leaf_row(leaf_key, value),
+        }
+    }
+    fn delete_branch_row(&mut self, branch_key: &BranchKey) -> Result<(), Error> {
+        match self {
+            AnyBackend::Postgres(backend) => backend.delete_branch_row(branch_key),
+            AnyBackend::Sqlite(backend) => backend.delete_branch_row(branch_key),
+            AnyBackend::Memory(backend) => backend.delete_branch_row(branch_key),
+        }
+    }
+    fn delete_leaf_row(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        match self {
+            AnyBackend::Postgres(backend) => backend.delete_leaf_row(leaf_key),
+            AnyBackend::Sqlite(backend) => backend.delete_leaf_row(leaf_key),
+            AnyBackend::Memory(backend) => backend.delete_leaf_row(leaf_key),
+        }
+    }
+}
+
+/// Open a `KvStore` against the backend `config` selects, abstracting
+/// away whether that's Postgres, SQLite, or an in-memory map.
+pub fn open_store<H: Hasher + Default>(
+    config: BackendConfig,
+) -> Result<KvStore<AnyBackend<H>, H>, Error> {
+    let backend = match config {
+        BackendConfig::Postgres(postgres_config) => {
+            AnyBackend::Postgres(PostgresStore::with_config(postgres_config)?)
+        }
+        BackendConfig::Sqlite(path) => AnyBackend::Sqlite(SqliteBackend::open(&path)?),
+        BackendConfig::Memory => AnyBackend::Memory(MemoryBackend::new()),
+    };
+    Ok(KvStore::new(backend))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestHasher;
+
+    impl Hasher for TestHasher {
+        fn write_h256(&mut self, _h: &H256) {}
+        fn finish(self) -> H256 {
+            H256::zero()
+        }
+    }
+
+    /// Covers the round-trip chunk0-1 is about: a `MergeWithZero` branch
+    /// side must come back out of a `KvStore` exactly as it went in,
+    /// rather than collapsing to its hash the way a bare `H256` encoding
+    /// would.
+    #[test]
+    fn kv_store_round_trips_merge_with_zero() {
+        let mut store = KvStore::<MemoryBackend, TestHasher>::new(MemoryBackend::new());
+        let branch_key = BranchKey {
+            height: 3,
+            node_key: H256::zero(),
+        };
+        let left = MergeValue::MergeWithZero {
+            base_node: H256::zero(),
+            zero_bits: H256::zero(),
+            zero_count: 7,
+        };
+        let right = MergeValue::Value(H256::zero());
+
+        store
+            .insert_branch(
+                branch_key.clone(),
+                BranchNode {
+                    left: left.clone(),
+                    right: right.clone(),
+                },
+            )
+            .unwrap();
+
+        let got = store.get_branch(&branch_key).unwrap().unwrap();
+        assert_eq!(got.left, left);
+        assert_eq!(got.right, right);
+    }
+
+    #[test]
+    fn tag_if_bare_only_tags_bare_32_byte_hashes() {
+        let bare = vec![7u8; 32];
+        let tagged = tag_if_bare(bare.clone());
+        assert_eq!(tagged[0], MERGE_TAG_VALUE);
+        assert_eq!(&tagged[1..], &bare[..]);
+
+        let already_tagged = vec![1u8; 66];
+        assert_eq!(tag_if_bare(already_tagged.clone()), already_tagged);
+    }
+}